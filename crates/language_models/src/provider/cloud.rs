@@ -11,13 +11,15 @@ use cloud_llm_client::{
     TOOL_USE_LIMIT_REACHED_HEADER_NAME, ZED_VERSION_HEADER_NAME,
 };
 use futures::{
-    AsyncBufReadExt, FutureExt, Stream, StreamExt, future::BoxFuture, stream::BoxStream,
+    AsyncBufReadExt, FutureExt, Stream, StreamExt, TryFutureExt, channel::mpsc,
+    future::BoxFuture, stream::BoxStream,
 };
 use google_ai::GoogleModelMode;
+use rand::Rng;
 use gpui::{
     AnyElement, AnyView, App, AsyncApp, Context, Entity, SemanticVersion, Subscription, Task,
 };
-use http_client::http::{HeaderMap, HeaderValue};
+use http_client::http::{HeaderMap, HeaderValue, header::RETRY_AFTER};
 use http_client::{AsyncBody, HttpClient, Method, Response, StatusCode};
 use language_model::{
     AuthenticateError, LanguageModel, LanguageModelCacheConfiguration,
@@ -30,11 +32,16 @@ use language_model::{
 use release_channel::AppVersion;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use settings::SettingsStore;
+use settings::{Settings, SettingsStore};
 use smol::io::{AsyncReadExt, BufReader};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::str::FromStr as _;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use thiserror::Error;
 use ui::{TintColor, prelude::*};
@@ -47,9 +54,554 @@ use crate::provider::open_ai::{OpenAiEventMapper, count_open_ai_tokens, into_ope
 const PROVIDER_ID: LanguageModelProviderId = language_model::ZED_CLOUD_PROVIDER_ID;
 const PROVIDER_NAME: LanguageModelProviderName = language_model::ZED_CLOUD_PROVIDER_NAME;
 
+/// Default number of times a completion request will retry a transient
+/// failure (429/502/503/504) before giving up.
+const DEFAULT_MAX_COMPLETION_RETRIES: u32 = 4;
+/// Base delay for exponential backoff between completion retries.
+const COMPLETION_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+/// Upper bound on the computed (pre-jitter) backoff delay.
+const COMPLETION_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// How long a cached model list is trusted. A cache older than this is
+/// ignored on startup rather than served from disk; a younger one is
+/// served while the background fetch runs.
+const MODELS_CACHE_STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Deserialize)]
+struct CachedListModelsResponse {
+    fetched_at: DateTime<Utc>,
+    response: ListModelsResponse,
+}
+
+fn models_cache_path() -> PathBuf {
+    paths::support_dir().join("zed-cloud-models-cache.json")
+}
+
+fn load_cached_models() -> Option<CachedListModelsResponse> {
+    let contents = std::fs::read_to_string(models_cache_path()).ok()?;
+    serde_json::from_str(&contents).log_err()
+}
+
+struct ExpandedModels {
+    models: Vec<Arc<cloud_llm_client::LanguageModel>>,
+    default_model: Option<Arc<cloud_llm_client::LanguageModel>>,
+    default_fast_model: Option<Arc<cloud_llm_client::LanguageModel>>,
+    recommended_models: Vec<Arc<cloud_llm_client::LanguageModel>>,
+}
+
+fn save_cached_models(response: &ListModelsResponse) {
+    let cached = CachedListModelsResponse {
+        fetched_at: Utc::now(),
+        response: response.clone(),
+    };
+    if let Some(contents) = serde_json::to_string(&cached).log_err() {
+        std::fs::write(models_cache_path(), contents).log_err();
+    }
+}
+
+/// Default number of cached token counts kept per model when the user
+/// hasn't configured `ZedDotDevSettings::token_count_cache_size`.
+const DEFAULT_TOKEN_COUNT_CACHE_SIZE: usize = 64;
+
+/// A small LRU cache of token counts keyed by a hash of the request that
+/// produced them, so identical (or mostly-unchanged) requests don't pay for
+/// a round trip to `/count_tokens` every time. Also remembers the last
+/// request counted for each model well enough to recount just its tail
+/// (see `reusable_prefix`), for the common as-you-type case where only a
+/// draft message at the end of the conversation is changing.
+struct TokenCountCache {
+    capacity: usize,
+    order: VecDeque<u64>,
+    entries: HashMap<u64, u64>,
+    last_counted: Option<LastCountedRequest>,
+}
+
+/// The token breakdown of the most recently counted request for a given
+/// model, kept around so a later request that only appends new messages,
+/// or only edits the final one, can recount just what changed.
+struct LastCountedRequest {
+    model_id: String,
+    /// Hash of everything in the request except `messages` (tools,
+    /// tool choice, temperature, ...). A tail recount is only safe to
+    /// reuse against this entry while the hash still matches, since
+    /// `total_tokens` bakes in that fixed overhead.
+    config_hash: u64,
+    /// Per-message content hash, in order.
+    message_hashes: Vec<u64>,
+    /// Total tokens across all of `message_hashes`.
+    total_tokens: u64,
+    /// Total tokens for every message except the last. `Some` only when
+    /// this request was itself counted as a tail recount of just its final
+    /// message; `None` after a full recount or a tail recount of several
+    /// messages, since there is then no way to isolate the last message's
+    /// contribution from the total.
+    tokens_before_last_message: Option<u64>,
+}
+
+impl TokenCountCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            order: VecDeque::new(),
+            entries: HashMap::new(),
+            last_counted: None,
+        }
+    }
+
+    fn get(&mut self, key: u64) -> Option<u64> {
+        let tokens = *self.entries.get(&key)?;
+        self.order.retain(|existing| *existing != key);
+        self.order.push_back(key);
+        Some(tokens)
+    }
+
+    fn insert(&mut self, key: u64, tokens: u64) {
+        if self.entries.insert(key, tokens).is_none() {
+            self.order.push_back(key);
+            if self.order.len() > self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.entries.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    /// If `message_hashes` shares a leading run of messages with the last
+    /// request counted for `model_id` — either that request's entire
+    /// history (new messages were appended) or everything but its final
+    /// message (only the draft changed) — returns how many leading
+    /// messages can be skipped and the cached token count covering them.
+    /// Requires `config_hash` (everything but `messages`) to match the
+    /// last counted request too, since the fixed overhead baked into the
+    /// cached total is only valid for that exact configuration.
+    fn reusable_prefix(
+        &self,
+        model_id: &str,
+        config_hash: u64,
+        message_hashes: &[u64],
+    ) -> Option<(usize, u64)> {
+        let last = self.last_counted.as_ref()?;
+        if last.model_id != model_id || last.config_hash != config_hash {
+            return None;
+        }
+
+        [
+            Some((last.message_hashes.len(), last.total_tokens)),
+            last.tokens_before_last_message
+                .map(|tokens| (last.message_hashes.len().saturating_sub(1), tokens)),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|(len, _)| *len > 0 && message_hashes.len() >= *len)
+        .find(|(len, _)| last.message_hashes[..*len] == message_hashes[..*len])
+    }
+
+    fn remember(
+        &mut self,
+        model_id: String,
+        config_hash: u64,
+        message_hashes: Vec<u64>,
+        total_tokens: u64,
+        tokens_before_last_message: Option<u64>,
+    ) {
+        self.last_counted = Some(LastCountedRequest {
+            model_id,
+            config_hash,
+            message_hashes,
+            total_tokens,
+            tokens_before_last_message,
+        });
+    }
+}
+
+/// Hashes the model id and the full request together so a cached token
+/// count is only ever reused for the exact request (including messages,
+/// tools, and temperature) that produced it.
+fn token_count_cache_key(model_id: &str, request: &LanguageModelRequest) -> Option<u64> {
+    let serialized = serde_json::to_string(request).ok()?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    model_id.hash(&mut hasher);
+    serialized.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Hashes everything in `request` except `messages` (tools, tool choice,
+/// temperature, ...), so a tail recount can tell whether the fixed
+/// overhead baked into a previously cached total still applies.
+fn non_message_config_hash(request: &LanguageModelRequest) -> Option<u64> {
+    let mut value = serde_json::to_value(request).ok()?;
+    let object = value.as_object_mut()?;
+    object.remove("messages");
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    value.to_string().hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Hashes each message in `request` individually (by its serialized JSON
+/// content), so a later request that shares a leading run of identical
+/// messages can reuse their already-counted tokens. Returns `None` if the
+/// request doesn't serialize to the `{ "messages": [...] }` shape this
+/// relies on.
+fn message_hashes(request: &LanguageModelRequest) -> Option<Vec<u64>> {
+    let value = serde_json::to_value(request).ok()?;
+    let messages = value.get("messages")?.as_array()?;
+    Some(
+        messages
+            .iter()
+            .map(|message| {
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                message.to_string().hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect(),
+    )
+}
+
+/// Returns a copy of `request` with its `messages` truncated to just the
+/// ones from `skip` onward, for recounting only the tail of a
+/// conversation whose leading messages were already counted. The fixed
+/// overhead of the request (`tools`, `tool_choice`) is cleared rather than
+/// left intact: that overhead is already folded into the cached
+/// `base_tokens` this tail count gets added to (see `plan_token_count`),
+/// so leaving it in would charge for it twice. `None` on any failure,
+/// since this cache is purely an optimization and any issue here should
+/// just fall back to counting the whole request.
+fn request_with_messages_from(
+    request: &LanguageModelRequest,
+    skip: usize,
+) -> Option<LanguageModelRequest> {
+    let mut value = serde_json::to_value(request).ok()?;
+    let object = value.as_object_mut()?;
+    let messages = object.get_mut("messages")?.as_array_mut()?;
+    if skip >= messages.len() {
+        return None;
+    }
+    *messages = messages.split_off(skip);
+    if let Some(tools) = object.get_mut("tools") {
+        *tools = serde_json::Value::Array(Vec::new());
+    }
+    if let Some(tool_choice) = object.get_mut("tool_choice") {
+        *tool_choice = serde_json::Value::Null;
+    }
+    serde_json::from_value(value).ok()
+}
+
+/// What to do to count tokens for a request: reuse a cached total outright
+/// for a byte-identical repeat, or recount some request (possibly just the
+/// tail of the original) and fold the result into the real total via
+/// `finish`.
+enum TokenCountPlan {
+    Cached(u64),
+    Count {
+        request: LanguageModelRequest,
+        finish: Box<dyn FnOnce(u64) -> u64 + Send>,
+    },
+}
+
+/// Decides how to count tokens for `request` on behalf of `model_id`: an
+/// exact cache hit, a tail recount building on a cached prefix (see
+/// `TokenCountCache::reusable_prefix`), or a full recount when neither
+/// applies.
+fn plan_token_count(
+    token_count_cache: Arc<Mutex<TokenCountCache>>,
+    model_id: String,
+    request: LanguageModelRequest,
+) -> TokenCountPlan {
+    let cache_key = token_count_cache_key(&model_id, &request);
+    if let Some(cache_key) = cache_key {
+        if let Some(tokens) = token_count_cache.lock().unwrap().get(cache_key) {
+            return TokenCountPlan::Cached(tokens);
+        }
+    }
+
+    let hashes = message_hashes(&request);
+    let config_hash = non_message_config_hash(&request);
+    let reusable = config_hash.zip(hashes.as_ref()).and_then(|(config_hash, hashes)| {
+        token_count_cache
+            .lock()
+            .unwrap()
+            .reusable_prefix(&model_id, config_hash, hashes)
+    });
+    let full_len = hashes.as_ref().map(Vec::len);
+
+    let (count_request, base_tokens, tail_start) = match reusable {
+        Some((shared, base_tokens)) if full_len.is_some_and(|len| shared < len) => {
+            match request_with_messages_from(&request, shared) {
+                Some(tail_request) => (tail_request, base_tokens, Some(shared)),
+                None => (request, 0, None),
+            }
+        }
+        _ => (request, 0, None),
+    };
+
+    TokenCountPlan::Count {
+        request: count_request,
+        finish: Box::new(move |tail_tokens| {
+            let total = base_tokens + tail_tokens;
+            if let Some(cache_key) = cache_key {
+                token_count_cache.lock().unwrap().insert(cache_key, total);
+            }
+            if let Some((config_hash, hashes)) = config_hash.zip(hashes) {
+                let tokens_before_last_message = tail_start
+                    .filter(|shared| shared + 1 == hashes.len())
+                    .map(|_| base_tokens);
+                token_count_cache.lock().unwrap().remember(
+                    model_id,
+                    config_hash,
+                    hashes,
+                    total,
+                    tokens_before_last_message,
+                );
+            }
+            total
+        }),
+    }
+}
+
+fn is_retryable_completion_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Computes the delay to wait before the next retry attempt using
+/// exponential backoff with full jitter: `rand(0, min(max, base * 2^attempt))`.
+fn completion_retry_backoff(attempt: u32, base_delay: Duration) -> Duration {
+    let exponential = base_delay.saturating_mul(1u32 << attempt.min(16));
+    let capped = exponential.min(COMPLETION_RETRY_MAX_DELAY);
+    Duration::from_millis(rand::thread_rng().gen_range(0..=capped.as_millis() as u64))
+}
+
+/// Parses the standard `Retry-After` header, which may be either a number
+/// of seconds or an HTTP-date, per RFC 9110 section 10.2.3.
+fn parse_retry_after_header(headers: &HeaderMap<HeaderValue>) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?;
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc);
+    (date - Utc::now()).to_std().ok()
+}
+
+fn header_str<'a>(headers: &'a HeaderMap<HeaderValue>, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok()
+}
+
+/// De-facto standard rate-limit information recovered from response
+/// headers, used as a fallback when the JSON body doesn't carry its own
+/// `retry_after` and recorded for telemetry/UI regardless. Header lookups
+/// are case-insensitive, since `HeaderName` normalizes to lowercase.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct RateLimitHeaders {
+    retry_after: Option<Duration>,
+    remaining_requests: Option<u64>,
+    remaining_tokens: Option<u64>,
+    reset_requests: Option<String>,
+    reset_tokens: Option<String>,
+}
+
+fn parse_rate_limit_headers(headers: &HeaderMap<HeaderValue>) -> RateLimitHeaders {
+    RateLimitHeaders {
+        retry_after: parse_retry_after_header(headers),
+        remaining_requests: header_str(headers, "x-ratelimit-remaining-requests")
+            .and_then(|value| value.parse().ok()),
+        remaining_tokens: header_str(headers, "x-ratelimit-remaining-tokens")
+            .and_then(|value| value.parse().ok()),
+        reset_requests: header_str(headers, "x-ratelimit-reset-requests").map(str::to_string),
+        reset_tokens: header_str(headers, "x-ratelimit-reset-tokens").map(str::to_string),
+    }
+}
+
+impl RateLimitHeaders {
+    /// Logs any remaining-quota values present, since
+    /// `LanguageModelCompletionError` has no field to carry them through to
+    /// the UI, but they're still worth keeping in telemetry.
+    fn log_remaining_quota(&self) {
+        if self.remaining_requests.is_some() || self.remaining_tokens.is_some() {
+            log::debug!(
+                "zed.dev rate limit headers: remaining_requests={:?} remaining_tokens={:?} reset_requests={:?} reset_tokens={:?}",
+                self.remaining_requests,
+                self.remaining_tokens,
+                self.reset_requests,
+                self.reset_tokens,
+            );
+        }
+    }
+}
+
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct ZedDotDevSettings {
     pub available_models: Vec<AvailableModel>,
+    /// The id of a model to fall back to when a request fails with an error
+    /// that `is_hard_model_limit_error` accepts (a hard subscription or rate
+    /// limit, a persistent upstream error, or a missed deadline), after
+    /// `default_fast_model` has already been tried.
+    pub fallback_model_id: Option<String>,
+    /// Per-model ordered fallback lists, keyed by the originating model's
+    /// id. Takes priority over `fallback_model_id` when the requested model
+    /// has an entry here.
+    pub model_fallbacks: HashMap<String, Vec<String>>,
+    /// Maximum number of times a completion request will retry a transient
+    /// or retryable upstream failure before giving up. Defaults to
+    /// `DEFAULT_MAX_COMPLETION_RETRIES` when unset.
+    pub completion_retry_max_attempts: Option<u32>,
+    /// Base delay, in milliseconds, for the exponential backoff between
+    /// completion retries. Defaults to `COMPLETION_RETRY_BASE_DELAY` when
+    /// unset.
+    pub completion_retry_base_delay_ms: Option<u64>,
+    /// The number of tokens to budget for reasoning/thinking on models that
+    /// support it. Defaults to `DEFAULT_THINKING_BUDGET_TOKENS` when unset.
+    /// Clamped below the model's `max_output_tokens`.
+    pub thinking_budget_tokens: Option<u32>,
+    /// Maximum number of distinct requests to keep cached token counts for,
+    /// per model. Defaults to `DEFAULT_TOKEN_COUNT_CACHE_SIZE` when unset.
+    pub token_count_cache_size: Option<usize>,
+    /// Per-request deadline, in milliseconds, for a Zed Cloud completion.
+    /// Covers both the time to the initial response and stalls between
+    /// subsequent bytes once streaming has started. Defaults to
+    /// `DEFAULT_COMPLETION_DEADLINE` when unset; disabled entirely when set
+    /// to `0`.
+    pub completion_deadline_ms: Option<u64>,
+}
+
+/// Default reasoning/thinking budget used when a `-thinking` model variant
+/// is requested and `ZedDotDevSettings::thinking_budget_tokens` is unset.
+const DEFAULT_THINKING_BUDGET_TOKENS: u32 = 4_096;
+
+/// Resolves the number of tokens to budget for reasoning, preferring
+/// `configured` but always keeping it lower than `max_output_tokens`.
+fn resolved_thinking_budget_tokens(configured: Option<u32>, max_output_tokens: u64) -> u32 {
+    let budget = configured.unwrap_or(DEFAULT_THINKING_BUDGET_TOKENS);
+    let max_output_tokens = u32::try_from(max_output_tokens).unwrap_or(u32::MAX);
+    budget.min(max_output_tokens.saturating_sub(1))
+}
+
+/// Maps a thinking token budget onto the closest OpenAI reasoning effort
+/// tier, for models that take a coarse effort level rather than a token
+/// budget.
+fn reasoning_effort_for_budget(budget_tokens: u32) -> open_ai::ReasoningEffort {
+    match budget_tokens {
+        0..=2_047 => open_ai::ReasoningEffort::Low,
+        2_048..=8_191 => open_ai::ReasoningEffort::Medium,
+        _ => open_ai::ReasoningEffort::High,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CompletionRetryConfig {
+    max_attempts: u32,
+    base_delay: Duration,
+}
+
+impl CompletionRetryConfig {
+    fn from_settings(settings: &ZedDotDevSettings) -> Self {
+        Self {
+            max_attempts: settings
+                .completion_retry_max_attempts
+                .unwrap_or(DEFAULT_MAX_COMPLETION_RETRIES),
+            base_delay: settings
+                .completion_retry_base_delay_ms
+                .map(Duration::from_millis)
+                .unwrap_or(COMPLETION_RETRY_BASE_DELAY),
+        }
+    }
+}
+
+impl Default for CompletionRetryConfig {
+    fn default() -> Self {
+        Self::from_settings(&ZedDotDevSettings::default())
+    }
+}
+
+/// Default per-request deadline for a Zed Cloud completion, covering both
+/// the time to the initial response and stalls between subsequent bytes
+/// once streaming has started.
+const DEFAULT_COMPLETION_DEADLINE: Duration = Duration::from_secs(120);
+
+/// The resolved deadline to apply to a Zed Cloud completion request.
+/// `None` means the deadline is disabled (the user configured `0`).
+#[derive(Clone, Copy, Debug)]
+struct CompletionDeadline(Option<Duration>);
+
+impl CompletionDeadline {
+    fn from_settings(settings: &ZedDotDevSettings) -> Self {
+        let deadline = settings
+            .completion_deadline_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_COMPLETION_DEADLINE);
+        Self((!deadline.is_zero()).then_some(deadline))
+    }
+}
+
+impl Default for CompletionDeadline {
+    fn default() -> Self {
+        Self::from_settings(&ZedDotDevSettings::default())
+    }
+}
+
+/// Raised when a Zed Cloud completion exceeds its configured
+/// `CompletionDeadline`, either waiting on the initial response or stalled
+/// mid-stream with no new bytes. Kept as a dedicated type (rather than a
+/// generic network/IO error) so callers, including `ShouldRetry`, can
+/// recognize deadline exceedance specifically.
+#[derive(Debug, Error)]
+#[error("cloud language model request exceeded its {0:?} deadline")]
+struct CompletionDeadlineExceeded(Duration);
+
+/// Races `future` against `deadline`, returning `CompletionDeadlineExceeded`
+/// if the deadline elapses first. Passing `None` disables the deadline and
+/// simply awaits `future`.
+async fn with_deadline<T>(
+    deadline: Option<Duration>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(deadline) = deadline else {
+        return future.await;
+    };
+
+    futures::pin_mut!(future);
+    match futures::future::select(future, smol::Timer::after(deadline)).await {
+        futures::future::Either::Left((result, _)) => result,
+        futures::future::Either::Right(_) => Err(anyhow!(CompletionDeadlineExceeded(deadline))),
+    }
+}
+
+/// Wraps a completion event stream so that every item (and the stream's
+/// end) must arrive within `deadline` of the previous one, surfacing a
+/// `CompletionDeadlineExceeded` error instead of stalling forever when the
+/// upstream connection goes quiet mid-stream. Passing `None` disables the
+/// wrapper and yields `stream` unchanged.
+fn with_stall_deadline<T: 'static>(
+    deadline: Option<Duration>,
+    stream: impl Stream<Item = Result<T>> + Send + 'static,
+) -> Pin<Box<dyn Stream<Item = Result<T>> + Send>> {
+    let Some(deadline) = deadline else {
+        return stream.boxed();
+    };
+
+    futures::stream::unfold(
+        (Box::pin(stream), false),
+        move |(mut stream, exhausted)| async move {
+            if exhausted {
+                return None;
+            }
+            match futures::future::select(stream.next(), smol::Timer::after(deadline)).await {
+                futures::future::Either::Left((Some(item), _)) => Some((item, (stream, false))),
+                futures::future::Either::Left((None, _)) => None,
+                futures::future::Either::Right(_) => Some((
+                    Err(anyhow!(CompletionDeadlineExceeded(deadline))),
+                    (stream, true),
+                )),
+            }
+        },
+    )
+    .boxed()
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -139,6 +691,39 @@ impl State {
     ) -> Self {
         let refresh_llm_token_listener = RefreshLlmTokenListener::global(cx);
 
+        // Hydrate from the on-disk cache immediately so the model picker
+        // isn't empty while we wait on authentication and the network, even
+        // across restarts. The background task below will replace this with
+        // a fresh response once it's signed in. A cache older than
+        // `MODELS_CACHE_STALE_AFTER` is ignored entirely rather than
+        // hydrated from, so a machine that's been offline for a long time
+        // doesn't keep serving an arbitrarily old model list if auth or the
+        // fetch never succeeds.
+        let cached = load_cached_models().filter(|cached| {
+            let age = Utc::now().signed_duration_since(cached.fetched_at);
+            let is_stale = age
+                > chrono::Duration::from_std(MODELS_CACHE_STALE_AFTER)
+                    .unwrap_or(chrono::Duration::MAX);
+            if is_stale {
+                log::debug!("cached zed.dev model list is stale, ignoring until refreshed");
+            }
+            !is_stale
+        });
+        let ExpandedModels {
+            models,
+            default_model,
+            default_fast_model,
+            recommended_models,
+        } = cached
+            .as_ref()
+            .map(|cached| Self::expand_models(&cached.response))
+            .unwrap_or(ExpandedModels {
+                models: Vec::new(),
+                default_model: None,
+                default_fast_model: None,
+                recommended_models: Vec::new(),
+            });
+
         Self {
             client: client.clone(),
             llm_api_token: LlmApiToken::default(),
@@ -146,10 +731,10 @@ impl State {
             cloud_user_store,
             status,
             accept_terms_of_service_task: None,
-            models: Vec::new(),
-            default_model: None,
-            default_fast_model: None,
-            recommended_models: Vec::new(),
+            models,
+            default_model,
+            default_fast_model,
+            recommended_models,
             _fetch_models_task: cx.spawn(async move |this, cx| {
                 maybe!(async move {
                     let (client, cloud_user_store, llm_api_token) =
@@ -207,6 +792,48 @@ impl State {
         !self.cloud_user_store.read(cx).is_authenticated()
     }
 
+    /// Builds the ordered list of models to fall back to if `model` hits a
+    /// persistent upstream error or a hard subscription/rate limit: the
+    /// user's configured per-model fallback list
+    /// (`ZedDotDevSettings::model_fallbacks`) if one exists for this model,
+    /// otherwise the account's fast default followed by
+    /// `ZedDotDevSettings::fallback_model_id`.
+    fn fallback_chain_for(
+        &self,
+        model: &Arc<cloud_llm_client::LanguageModel>,
+        cx: &App,
+    ) -> Vec<Arc<cloud_llm_client::LanguageModel>> {
+        let settings = ZedDotDevSettings::get_global(cx);
+        let mut chain = Vec::new();
+
+        let mut push_by_id = |chain: &mut Vec<Arc<cloud_llm_client::LanguageModel>>, id: &str| {
+            if let Some(candidate) = self.models.iter().find(|m| m.id.0.as_ref() == id) {
+                if candidate.id != model.id && !chain.iter().any(|m| m.id == candidate.id) {
+                    chain.push(candidate.clone());
+                }
+            }
+        };
+
+        if let Some(configured) = settings.model_fallbacks.get(model.id.0.as_ref()) {
+            for fallback_id in configured {
+                push_by_id(&mut chain, fallback_id);
+            }
+            return chain;
+        }
+
+        if let Some(fast_model) = &self.default_fast_model {
+            if fast_model.id != model.id {
+                chain.push(fast_model.clone());
+            }
+        }
+
+        if let Some(fallback_id) = settings.fallback_model_id.as_deref() {
+            push_by_id(&mut chain, fallback_id);
+        }
+
+        chain
+    }
+
     fn authenticate(&self, cx: &mut Context<Self>) -> Task<Result<()>> {
         let client = self.client.clone();
         cx.spawn(async move |state, cx| {
@@ -235,10 +862,14 @@ impl State {
         }));
     }
 
-    fn update_models(&mut self, response: ListModelsResponse, cx: &mut Context<Self>) {
+    /// Expands a `ListModelsResponse` into the model list this provider
+    /// exposes, inserting a `-thinking` variant for every model that
+    /// supports thinking, along with the resolved default/fast/recommended
+    /// models.
+    fn expand_models(response: &ListModelsResponse) -> ExpandedModels {
         let mut models = Vec::new();
 
-        for model in response.models {
+        for model in response.models.iter().cloned() {
             models.push(Arc::new(model.clone()));
 
             // Right now we represent thinking variants of models as separate models on the client,
@@ -252,20 +883,42 @@ impl State {
             }
         }
 
-        self.default_model = models
+        let default_model = models
             .iter()
             .find(|model| model.id == response.default_model)
             .cloned();
-        self.default_fast_model = models
+        let default_fast_model = models
             .iter()
             .find(|model| model.id == response.default_fast_model)
             .cloned();
-        self.recommended_models = response
+        let recommended_models = response
             .recommended_models
             .iter()
             .filter_map(|id| models.iter().find(|model| &model.id == id))
             .cloned()
             .collect();
+
+        ExpandedModels {
+            models,
+            default_model,
+            default_fast_model,
+            recommended_models,
+        }
+    }
+
+    fn update_models(&mut self, response: ListModelsResponse, cx: &mut Context<Self>) {
+        save_cached_models(&response);
+
+        let ExpandedModels {
+            models,
+            default_model,
+            default_fast_model,
+            recommended_models,
+        } = Self::expand_models(&response);
+
+        self.default_model = default_model;
+        self.default_fast_model = default_fast_model;
+        self.recommended_models = recommended_models;
         self.models = models;
         cx.notify();
     }
@@ -349,13 +1002,20 @@ impl CloudLanguageModelProvider {
         &self,
         model: Arc<cloud_llm_client::LanguageModel>,
         llm_api_token: LlmApiToken,
+        cx: &App,
     ) -> Arc<dyn LanguageModel> {
+        let fallback_chain = self.state.read(cx).fallback_chain_for(&model, cx);
+        let token_count_cache_size = ZedDotDevSettings::get_global(cx)
+            .token_count_cache_size
+            .unwrap_or(DEFAULT_TOKEN_COUNT_CACHE_SIZE);
         Arc::new(CloudLanguageModel {
             id: LanguageModelId(SharedString::from(model.id.0.clone())),
             model,
             llm_api_token: llm_api_token.clone(),
             client: self.client.clone(),
             request_limiter: RateLimiter::new(4),
+            fallback_chain,
+            token_count_cache: Arc::new(Mutex::new(TokenCountCache::new(token_count_cache_size))),
         })
     }
 }
@@ -384,13 +1044,13 @@ impl LanguageModelProvider for CloudLanguageModelProvider {
     fn default_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
         let default_model = self.state.read(cx).default_model.clone()?;
         let llm_api_token = self.state.read(cx).llm_api_token.clone();
-        Some(self.create_language_model(default_model, llm_api_token))
+        Some(self.create_language_model(default_model, llm_api_token, cx))
     }
 
     fn default_fast_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
         let default_fast_model = self.state.read(cx).default_fast_model.clone()?;
         let llm_api_token = self.state.read(cx).llm_api_token.clone();
-        Some(self.create_language_model(default_fast_model, llm_api_token))
+        Some(self.create_language_model(default_fast_model, llm_api_token, cx))
     }
 
     fn recommended_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
@@ -400,7 +1060,7 @@ impl LanguageModelProvider for CloudLanguageModelProvider {
             .recommended_models
             .iter()
             .cloned()
-            .map(|model| self.create_language_model(model, llm_api_token.clone()))
+            .map(|model| self.create_language_model(model, llm_api_token.clone(), cx))
             .collect()
     }
 
@@ -411,7 +1071,7 @@ impl LanguageModelProvider for CloudLanguageModelProvider {
             .models
             .iter()
             .cloned()
-            .map(|model| self.create_language_model(model, llm_api_token.clone()))
+            .map(|model| self.create_language_model(model, llm_api_token.clone(), cx))
             .collect()
     }
 
@@ -541,6 +1201,12 @@ pub struct CloudLanguageModel {
     llm_api_token: LlmApiToken,
     client: Arc<Client>,
     request_limiter: RateLimiter,
+    /// Models to transparently re-attempt the same request against, in
+    /// order, when this model fails with an error that
+    /// `is_hard_model_limit_error` accepts (a hard subscription or rate
+    /// limit, a persistent upstream error, or a missed deadline).
+    fallback_chain: Vec<Arc<cloud_llm_client::LanguageModel>>,
+    token_count_cache: Arc<Mutex<TokenCountCache>>,
 }
 
 struct PerformLlmCompletionResponse {
@@ -551,16 +1217,25 @@ struct PerformLlmCompletionResponse {
 }
 
 impl CloudLanguageModel {
+    /// Sends `retry_status_tx` a `CompletionRequestStatus::Retrying` before
+    /// every retry attempt (both the status-code-derived and body-derived
+    /// retry branches below), so the caller can surface a "retrying…"
+    /// status event through the completion stream even though this
+    /// function itself only returns a single response, not a stream.
     async fn perform_llm_completion(
         client: Arc<Client>,
         llm_api_token: LlmApiToken,
         app_version: Option<SemanticVersion>,
         body: CompletionBody,
+        retry_config: CompletionRetryConfig,
+        deadline: CompletionDeadline,
+        retry_status_tx: mpsc::UnboundedSender<CompletionRequestStatus>,
     ) -> Result<PerformLlmCompletionResponse> {
         let http_client = &client.http_client();
 
         let mut token = llm_api_token.acquire(&client).await?;
         let mut refreshed_token = false;
+        let mut retries = 0;
 
         loop {
             let request_builder = http_client::Request::builder()
@@ -577,7 +1252,8 @@ impl CloudLanguageModel {
                 .header("Authorization", format!("Bearer {token}"))
                 .header(CLIENT_SUPPORTS_STATUS_MESSAGES_HEADER_NAME, "true")
                 .body(serde_json::to_string(&body)?.into())?;
-            let mut response = http_client.send(request).await?;
+            let mut response =
+                with_deadline(deadline.0, http_client.send(request)).await?;
             let status = response.status();
             if status.is_success() {
                 let includes_status_messages = response
@@ -615,6 +1291,22 @@ impl CloudLanguageModel {
                 continue;
             }
 
+            if is_retryable_completion_status(status) && retries < retry_config.max_attempts {
+                let delay = parse_retry_after_header(response.headers())
+                    .unwrap_or_else(|| completion_retry_backoff(retries, retry_config.base_delay));
+                retries += 1;
+                log::info!(
+                    "zed.dev completion request failed with status {status}, retrying (attempt {retries}/{})",
+                    retry_config.max_attempts
+                );
+                let _ = retry_status_tx.unbounded_send(CompletionRequestStatus::Retrying {
+                    attempt: retries,
+                    max_attempts: retry_config.max_attempts,
+                });
+                smol::Timer::after(delay).await;
+                continue;
+            }
+
             if status == StatusCode::FORBIDDEN
                 && response
                     .headers()
@@ -647,6 +1339,46 @@ impl CloudLanguageModel {
             let mut body = String::new();
             let headers = response.headers().clone();
             response.body_mut().read_to_string(&mut body).await?;
+
+            if retries < retry_config.max_attempts {
+                // Classify the error the same way a fallback caller would
+                // (`ShouldRetry::retry_policy`), so a permanent
+                // control-plane rejection (account suspended, region
+                // blocked, quota exceeded) fails fast here instead of
+                // retrying against the same model first. A rejection sent
+                // with a status `is_retryable_completion_status` accepts
+                // has already been retried by the status check above,
+                // which does not read the body.
+                let retry_policy = LanguageModelCompletionError::from(ApiError {
+                    status,
+                    body: body.clone(),
+                    headers: headers.clone(),
+                })
+                .retry_policy();
+
+                let delay = match retry_policy {
+                    RetryPolicy::RetryAfter(delay) => Some(delay),
+                    RetryPolicy::RetryWithBackoff => {
+                        Some(completion_retry_backoff(retries, retry_config.base_delay))
+                    }
+                    RetryPolicy::NonRetryable => None,
+                };
+
+                if let Some(delay) = delay {
+                    retries += 1;
+                    log::info!(
+                        "zed.dev completion request reported a retryable upstream error, retrying (attempt {retries}/{})",
+                        retry_config.max_attempts
+                    );
+                    let _ = retry_status_tx.unbounded_send(CompletionRequestStatus::Retrying {
+                        attempt: retries,
+                        max_attempts: retry_config.max_attempts,
+                    });
+                    smol::Timer::after(delay).await;
+                    continue;
+                }
+            }
+
             return Err(anyhow!(ApiError {
                 status,
                 body,
@@ -656,7 +1388,7 @@ impl CloudLanguageModel {
     }
 }
 
-#[derive(Debug, Error)]
+#[derive(Error)]
 #[error("cloud language model request failed with status {status}: {body}")]
 struct ApiError {
     status: StatusCode,
@@ -664,6 +1396,48 @@ struct ApiError {
     headers: HeaderMap<HeaderValue>,
 }
 
+/// Header names that commonly carry credentials, and so should never appear
+/// verbatim in logs, telemetry, or test failure output.
+fn is_sensitive_header_name(name: &str) -> bool {
+    matches!(
+        name.to_ascii_lowercase().as_str(),
+        "authorization" | "proxy-authorization" | "cookie" | "set-cookie"
+    ) || name.to_ascii_lowercase().ends_with("-key")
+}
+
+/// Wraps a `HeaderMap` so that sensitive headers (see
+/// `is_sensitive_header_name`) are redacted to `<masked>` wherever this
+/// wrapper is formatted, while other headers are preserved verbatim.
+struct RedactedHeaders<'a>(&'a HeaderMap<HeaderValue>);
+
+impl fmt::Debug for RedactedHeaders<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|(name, value)| {
+                let value = if is_sensitive_header_name(name.as_str()) {
+                    "<masked>"
+                } else {
+                    value.to_str().unwrap_or("<invalid>")
+                };
+                (name.as_str(), value)
+            }))
+            .finish()
+    }
+}
+
+/// Manual `Debug` impl so that `Authorization`/API-key/cookie headers are
+/// never leaked into logs or telemetry via `detach_and_log_err` or a test
+/// panic message; `status` and `body` are still printed verbatim.
+impl fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiError")
+            .field("status", &self.status)
+            .field("body", &self.body)
+            .field("headers", &RedactedHeaders(&self.headers))
+            .finish()
+    }
+}
+
 /// Represents error responses from Zed's cloud API.
 ///
 /// Example JSON for an upstream HTTP error:
@@ -693,8 +1467,121 @@ where
     Ok(opt.and_then(|code| StatusCode::from_u16(code).ok()))
 }
 
+/// A reason surfaced by Zed's cloud control plane for why a request was
+/// rejected, e.g. a quota or account-standing issue rather than an upstream
+/// provider failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlPlaneErrorReason {
+    QuotaExceeded,
+    AccountSuspended,
+    RegionBlocked,
+    Unknown,
+}
+
+impl ControlPlaneErrorReason {
+    fn parse(reason: &str) -> Self {
+        match reason {
+            "QUOTA_EXCEEDED" => Self::QuotaExceeded,
+            "ACCOUNT_SUSPENDED" => Self::AccountSuspended,
+            "REGION_BLOCKED" => Self::RegionBlocked,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Richer control-plane error envelope Zed's cloud API can return in place
+/// of (or in addition to) the simpler `upstream_http_*` shape, e.g.:
+/// ```json
+/// {
+///   "status": {
+///     "details": {
+///       "error_info": { "reason": "QUOTA_EXCEEDED", "metadata": {} },
+///       "user_facing_message": { "message": "You've used all your included requests." }
+///     }
+///   }
+/// }
+/// ```
+#[derive(Debug, serde::Deserialize)]
+struct ControlPlaneError {
+    status: ControlPlaneErrorStatus,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ControlPlaneErrorStatus {
+    details: ControlPlaneErrorDetails,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ControlPlaneErrorDetails {
+    error_info: Option<ControlPlaneErrorInfo>,
+    user_facing_message: Option<ControlPlaneUserFacingMessage>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ControlPlaneErrorInfo {
+    reason: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ControlPlaneUserFacingMessage {
+    message: String,
+}
+
+/// Parses the nested control-plane error envelope, returning the typed
+/// reason (defaulting to `Unknown` when `error_info` is absent or its
+/// reason isn't recognized) along with the message to surface to the user.
+/// Returns `None` when `body` doesn't match this envelope shape at all, so
+/// callers can fall back to the simpler `upstream_http_*` handling.
+fn parse_control_plane_error(body: &str) -> Option<(ControlPlaneErrorReason, String)> {
+    let error: ControlPlaneError = serde_json::from_str(body).ok()?;
+    let reason = error
+        .status
+        .details
+        .error_info
+        .as_ref()
+        .map(|info| ControlPlaneErrorReason::parse(&info.reason))
+        .unwrap_or(ControlPlaneErrorReason::Unknown);
+    let message = error
+        .status
+        .details
+        .user_facing_message
+        .map(|m| m.message)
+        .unwrap_or_else(|| format!("Zed Cloud request failed ({reason:?})"));
+
+    Some((reason, message))
+}
+
 impl From<ApiError> for LanguageModelCompletionError {
     fn from(error: ApiError) -> Self {
+        let rate_limit_headers = parse_rate_limit_headers(&error.headers);
+        rate_limit_headers.log_remaining_quota();
+
+        if let Some((reason, message)) = parse_control_plane_error(&error.body) {
+            log::warn!("zed.dev control-plane error: reason={reason:?}, message={message:?}");
+
+            // `QuotaExceeded`/`AccountSuspended`/`RegionBlocked` are
+            // permanent for the lifetime of this request no matter what
+            // HTTP status or `Retry-After` header the control plane
+            // happened to send, so normalize to a status `retry_policy`
+            // will never treat as transient. `Unknown` keeps the
+            // status/retry-after we were actually given, since we can't
+            // be sure it isn't transient.
+            let (status, retry_after) = match reason {
+                ControlPlaneErrorReason::QuotaExceeded
+                | ControlPlaneErrorReason::AccountSuspended
+                | ControlPlaneErrorReason::RegionBlocked => (StatusCode::FORBIDDEN, None),
+                ControlPlaneErrorReason::Unknown => {
+                    (error.status, rate_limit_headers.retry_after)
+                }
+            };
+
+            return LanguageModelCompletionError::UpstreamProviderError {
+                message,
+                status,
+                retry_after,
+            };
+        }
+
         if let Ok(cloud_error) = serde_json::from_str::<CloudApiError>(&error.body) {
             if cloud_error.code.starts_with("upstream_http_") {
                 let status = if let Some(status) = cloud_error.upstream_status {
@@ -712,20 +1599,26 @@ impl From<ApiError> for LanguageModelCompletionError {
                         .unwrap_or(error.status)
                 };
 
+                // Prefer a `retry_after` carried in the body, but fall back
+                // to the one recovered from headers when the body has none.
+                let retry_after = cloud_error
+                    .retry_after
+                    .map(Duration::from_secs_f64)
+                    .or(rate_limit_headers.retry_after);
+
                 return LanguageModelCompletionError::UpstreamProviderError {
                     message: cloud_error.message,
                     status,
-                    retry_after: cloud_error.retry_after.map(Duration::from_secs_f64),
+                    retry_after,
                 };
             }
         }
 
-        let retry_after = None;
         LanguageModelCompletionError::from_http_status(
             PROVIDER_NAME,
             error.status,
             error.body,
-            retry_after,
+            rate_limit_headers.retry_after,
         )
     }
 }
@@ -833,19 +1726,43 @@ impl LanguageModel for CloudLanguageModel {
     ) -> BoxFuture<'static, Result<u64>> {
         match self.model.provider {
             cloud_llm_client::LanguageModelProvider::Anthropic => {
-                count_anthropic_tokens(request, cx)
+                let token_count_cache = self.token_count_cache.clone();
+                let model_id = self.model.id.to_string();
+                match plan_token_count(token_count_cache, model_id, request) {
+                    TokenCountPlan::Cached(tokens) => async move { Ok(tokens) }.boxed(),
+                    TokenCountPlan::Count { request, finish } => {
+                        count_anthropic_tokens(request, cx).map_ok(finish).boxed()
+                    }
+                }
             }
             cloud_llm_client::LanguageModelProvider::OpenAi => {
                 let model = match open_ai::Model::from_id(&self.model.id.0) {
                     Ok(model) => model,
                     Err(err) => return async move { Err(anyhow!(err)) }.boxed(),
                 };
-                count_open_ai_tokens(request, model, cx)
+                let token_count_cache = self.token_count_cache.clone();
+                let model_id = self.model.id.to_string();
+                match plan_token_count(token_count_cache, model_id, request) {
+                    TokenCountPlan::Cached(tokens) => async move { Ok(tokens) }.boxed(),
+                    TokenCountPlan::Count { request, finish } => {
+                        count_open_ai_tokens(request, model, cx).map_ok(finish).boxed()
+                    }
+                }
             }
             cloud_llm_client::LanguageModelProvider::Google => {
                 let client = self.client.clone();
                 let llm_api_token = self.llm_api_token.clone();
                 let model_id = self.model.id.to_string();
+                let token_count_cache = self.token_count_cache.clone();
+
+                let (request, finish) =
+                    match plan_token_count(token_count_cache, model_id.clone(), request) {
+                        TokenCountPlan::Cached(tokens) => {
+                            return async move { Ok(tokens) }.boxed();
+                        }
+                        TokenCountPlan::Count { request, finish } => (request, finish),
+                    };
+
                 let generate_content_request =
                     into_google(request, model_id.clone(), GoogleModelMode::Default);
                 async move {
@@ -881,8 +1798,7 @@ impl LanguageModel for CloudLanguageModel {
                     if status.is_success() {
                         let response_body: CountTokensResponse =
                             serde_json::from_str(&response_body)?;
-
-                        Ok(response_body.tokens as u64)
+                        Ok(finish(response_body.tokens as u64))
                     } else {
                         Err(anyhow!(ApiError {
                             status,
@@ -906,31 +1822,261 @@ impl LanguageModel for CloudLanguageModel {
             BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
             LanguageModelCompletionError,
         >,
+    > {
+        let app_version = cx.update(|cx| AppVersion::global(cx)).ok();
+        let (retry_config, thinking_budget_tokens, deadline) = cx
+            .update(|cx| {
+                let settings = ZedDotDevSettings::get_global(cx);
+                (
+                    CompletionRetryConfig::from_settings(settings),
+                    settings.thinking_budget_tokens,
+                    CompletionDeadline::from_settings(settings),
+                )
+            })
+            .unwrap_or_default();
+
+        if self.fallback_chain.is_empty() {
+            return Self::stream_completion_with_model(
+                self.model.clone(),
+                self.client.clone(),
+                self.llm_api_token.clone(),
+                self.request_limiter.clone(),
+                request,
+                app_version,
+                retry_config,
+                thinking_budget_tokens,
+                deadline,
+            );
+        }
+
+        let mut chain = Vec::with_capacity(1 + self.fallback_chain.len());
+        chain.push(self.model.clone());
+        chain.extend(self.fallback_chain.iter().cloned());
+        let client = self.client.clone();
+        let llm_api_token = self.llm_api_token.clone();
+        let request_limiter = self.request_limiter.clone();
+
+        async move {
+            let mut last_error = None;
+            for (index, model) in chain.iter().enumerate() {
+                let is_last_attempt = index + 1 == chain.len();
+                let result = Self::stream_completion_with_model(
+                    model.clone(),
+                    client.clone(),
+                    llm_api_token.clone(),
+                    request_limiter.clone(),
+                    request.clone(),
+                    app_version,
+                    retry_config,
+                    thinking_budget_tokens,
+                    deadline,
+                )
+                .await;
+
+                match result {
+                    Ok(stream) => {
+                        if index > 0 {
+                            log::warn!(
+                                "zed.dev completion fell back to model `{}` after a hard limit on the requested model",
+                                model.id
+                            );
+                        }
+                        return Ok(stream);
+                    }
+                    Err(error) if !is_last_attempt && is_hard_model_limit_error(&error) => {
+                        last_error = Some(error);
+                        continue;
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+            Err(last_error.expect("chain is non-empty, so at least one attempt was made"))
+        }
+        .boxed()
+    }
+}
+
+/// How a completion failure should be handled by a caller that's willing to
+/// automatically retry: not at all, after a provider-supplied delay, or
+/// with our own exponential backoff when the provider didn't give one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RetryPolicy {
+    /// The failure won't resolve itself on retry (auth, validation, a
+    /// genuine internal error), so it should be surfaced to the user.
+    NonRetryable,
+    /// Retry after the given provider-supplied delay.
+    RetryAfter(Duration),
+    /// Retry using the completion retry config's exponential backoff,
+    /// since no provider-supplied delay is available.
+    RetryWithBackoff,
+}
+
+/// Classifies whether a completion failure is safe to automatically retry
+/// (e.g. against a fallback model), and if so, how long to wait first.
+trait ShouldRetry {
+    fn retry_policy(&self) -> RetryPolicy;
+}
+
+impl ShouldRetry for LanguageModelCompletionError {
+    fn retry_policy(&self) -> RetryPolicy {
+        match self {
+            // Rate limits and overloaded/unavailable upstreams are
+            // transient by nature: retry, preferring whatever delay the
+            // provider asked for over our own guess. A missed
+            // `CompletionDeadline` (surfaced here as a 408, see
+            // `completion_deadline_exceeded_as_completion_error`) is just
+            // as transient — don't let it fall into the non-retryable
+            // default.
+            LanguageModelCompletionError::RateLimitExceeded { retry_after, .. }
+            | LanguageModelCompletionError::ServerOverloaded { retry_after, .. } => retry_after
+                .map(RetryPolicy::RetryAfter)
+                .unwrap_or(RetryPolicy::RetryWithBackoff),
+            LanguageModelCompletionError::HttpResponseError {
+                status_code: StatusCode::REQUEST_TIMEOUT,
+                ..
+            } => RetryPolicy::RetryWithBackoff,
+            LanguageModelCompletionError::UpstreamProviderError {
+                status,
+                retry_after,
+                ..
+            } if matches!(
+                *status,
+                StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::REQUEST_TIMEOUT
+            ) =>
+            {
+                retry_after
+                    .map(RetryPolicy::RetryAfter)
+                    .unwrap_or(RetryPolicy::RetryWithBackoff)
+            }
+            // `From<ApiError>` normalizes the permanent control-plane
+            // reasons (account suspended, region blocked, quota exceeded)
+            // to this status precisely so they land here instead of being
+            // retried, or silently burning through the fallback-model
+            // chain via `is_hard_model_limit_error`.
+            LanguageModelCompletionError::UpstreamProviderError {
+                status: StatusCode::FORBIDDEN,
+                ..
+            } => RetryPolicy::NonRetryable,
+            // Any other `UpstreamProviderError` is retried too:
+            // `perform_llm_completion` retries it against the same model
+            // with backoff until its attempts run out, and after that the
+            // fallback chain re-attempts it against the next model rather
+            // than surfacing it to the user.
+            LanguageModelCompletionError::UpstreamProviderError { .. } => {
+                RetryPolicy::RetryWithBackoff
+            }
+            // `ApiInternalServerError`, auth errors, and 4xx validation
+            // failures won't be fixed by retrying or falling back.
+            _ => RetryPolicy::NonRetryable,
+        }
+    }
+}
+
+/// Errors that warrant transparently re-attempting the request against the
+/// next model in the fallback chain, rather than surfacing to the user.
+fn is_hard_model_limit_error(error: &LanguageModelCompletionError) -> bool {
+    !matches!(error.retry_policy(), RetryPolicy::NonRetryable)
+}
+
+/// `perform_llm_completion` reports the `SUBSCRIPTION_LIMIT_RESOURCE_HEADER`
+/// model-requests case as a distinct `ModelRequestLimitReachedError`, not an
+/// `ApiError`, so callers must downcast to `ApiError` first (see
+/// `map_completion_error`, which each provider arm of
+/// `stream_completion_with_model` passes to `map_err`) and only fall
+/// through to this function for what's left. Map it to the same shape a 429 status would produce
+/// here so `is_hard_model_limit_error` recognizes it and falls back to the
+/// next model in the chain, same as any other hard rate limit.
+fn model_request_limit_reached_as_completion_error(err: anyhow::Error) -> anyhow::Error {
+    match err.downcast::<ModelRequestLimitReachedError>() {
+        Ok(err) => anyhow!(LanguageModelCompletionError::from_http_status(
+            PROVIDER_NAME,
+            StatusCode::TOO_MANY_REQUESTS,
+            err.to_string(),
+            None,
+        )),
+        Err(err) => err,
+    }
+}
+
+/// `with_deadline`/`with_stall_deadline` report a stall or missed deadline
+/// as a `CompletionDeadlineExceeded`, not an `ApiError`, so it never goes
+/// through `LanguageModelCompletionError`'s `From<ApiError>` conversion
+/// either. Map it to the same shape a 408 status would produce so it's
+/// distinguishable from a generic network error and `retry_policy`
+/// recognizes it as transient instead of silently falling into
+/// `RetryPolicy::NonRetryable`.
+fn completion_deadline_exceeded_as_completion_error(err: anyhow::Error) -> anyhow::Error {
+    match err.downcast::<CompletionDeadlineExceeded>() {
+        Ok(err) => anyhow!(LanguageModelCompletionError::from_http_status(
+            PROVIDER_NAME,
+            StatusCode::REQUEST_TIMEOUT,
+            err.to_string(),
+            None,
+        )),
+        Err(err) => err,
+    }
+}
+
+/// Converts the anyhow errors `perform_llm_completion` can fail with into a
+/// `LanguageModelCompletionError` the retry/fallback machinery can
+/// classify: an `ApiError` first (see `From<ApiError>` above), then a
+/// missed deadline, then the subscription model-request-limit case, with
+/// anything left over falling through to the blanket `From<anyhow::Error>`
+/// conversion via `?`.
+fn map_completion_error(err: anyhow::Error) -> anyhow::Error {
+    match err.downcast::<ApiError>() {
+        Ok(api_err) => anyhow!(LanguageModelCompletionError::from(api_err)),
+        Err(err) => model_request_limit_reached_as_completion_error(
+            completion_deadline_exceeded_as_completion_error(err),
+        ),
+    }
+}
+
+impl CloudLanguageModel {
+    fn stream_completion_with_model(
+        model: Arc<cloud_llm_client::LanguageModel>,
+        client: Arc<Client>,
+        llm_api_token: LlmApiToken,
+        request_limiter: RateLimiter,
+        request: LanguageModelRequest,
+        app_version: Option<SemanticVersion>,
+        retry_config: CompletionRetryConfig,
+        thinking_budget_tokens: Option<u32>,
+        deadline: CompletionDeadline,
+    ) -> BoxFuture<
+        'static,
+        Result<
+            BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+            LanguageModelCompletionError,
+        >,
     > {
         let thread_id = request.thread_id.clone();
         let prompt_id = request.prompt_id.clone();
         let intent = request.intent;
         let mode = request.mode;
-        let app_version = cx.update(|cx| AppVersion::global(cx)).ok();
         let thinking_allowed = request.thinking_allowed;
-        match self.model.provider {
+        match model.provider {
             cloud_llm_client::LanguageModelProvider::Anthropic => {
                 let request = into_anthropic(
                     request,
-                    self.model.id.to_string(),
+                    model.id.to_string(),
                     1.0,
-                    self.model.max_output_tokens as u64,
-                    if thinking_allowed && self.model.id.0.ends_with("-thinking") {
+                    model.max_output_tokens as u64,
+                    if thinking_allowed && model.id.0.ends_with("-thinking") {
                         AnthropicModelMode::Thinking {
-                            budget_tokens: Some(4_096),
+                            budget_tokens: Some(resolved_thinking_budget_tokens(
+                                thinking_budget_tokens,
+                                model.max_output_tokens as u64,
+                            )),
                         }
                     } else {
                         AnthropicModelMode::Default
                     },
                 );
-                let client = self.client.clone();
-                let llm_api_token = self.llm_api_token.clone();
-                let future = self.request_limiter.stream(async move {
+                let future = request_limiter.stream(async move {
+                    let (retry_status_tx, retry_status_rx) = mpsc::unbounded();
                     let PerformLlmCompletionResponse {
                         response,
                         usage,
@@ -950,39 +2096,47 @@ impl LanguageModel for CloudLanguageModel {
                             provider_request: serde_json::to_value(&request)
                                 .map_err(|e| anyhow!(e))?,
                         },
+                        retry_config,
+                        deadline,
+                        retry_status_tx,
                     )
                     .await
-                    .map_err(|err| match err.downcast::<ApiError>() {
-                        Ok(api_err) => anyhow!(LanguageModelCompletionError::from(api_err)),
-                        Err(err) => anyhow!(err),
-                    })?;
+                    .map_err(map_completion_error)?;
 
                     let mut mapper = AnthropicEventMapper::new();
                     Ok(map_cloud_completion_events(
-                        Box::pin(
-                            response_lines(response, includes_status_messages)
-                                .chain(usage_updated_event(usage))
-                                .chain(tool_use_limit_reached_event(tool_use_limit_reached)),
-                        ),
+                        retry_status_events(retry_status_rx)
+                            .chain(with_stall_deadline(deadline.0, response_lines(response, includes_status_messages)))
+                            .chain(usage_updated_event(usage))
+                            .chain(tool_use_limit_reached_event(tool_use_limit_reached))
+                            .boxed(),
                         move |event| mapper.map_event(event),
                     ))
                 });
                 async move { Ok(future.await?.boxed()) }.boxed()
             }
             cloud_llm_client::LanguageModelProvider::OpenAi => {
-                let client = self.client.clone();
-                let model = match open_ai::Model::from_id(&self.model.id.0) {
-                    Ok(model) => model,
+                let open_ai_model = match open_ai::Model::from_id(&model.id.0) {
+                    Ok(open_ai_model) => open_ai_model,
                     Err(err) => return async move { Err(anyhow!(err).into()) }.boxed(),
                 };
+                let reasoning_effort = if thinking_allowed && open_ai_model.id().ends_with("-thinking")
+                {
+                    Some(reasoning_effort_for_budget(resolved_thinking_budget_tokens(
+                        thinking_budget_tokens,
+                        model.max_output_tokens as u64,
+                    )))
+                } else {
+                    None
+                };
                 let request = into_open_ai(
                     request,
-                    model.id(),
-                    model.supports_parallel_tool_calls(),
-                    None,
+                    open_ai_model.id(),
+                    open_ai_model.supports_parallel_tool_calls(),
+                    reasoning_effort,
                 );
-                let llm_api_token = self.llm_api_token.clone();
-                let future = self.request_limiter.stream(async move {
+                let future = request_limiter.stream(async move {
+                    let (retry_status_tx, retry_status_rx) = mpsc::unbounded();
                     let PerformLlmCompletionResponse {
                         response,
                         usage,
@@ -1002,27 +2156,30 @@ impl LanguageModel for CloudLanguageModel {
                             provider_request: serde_json::to_value(&request)
                                 .map_err(|e| anyhow!(e))?,
                         },
+                        retry_config,
+                        deadline,
+                        retry_status_tx,
                     )
-                    .await?;
+                    .await
+                    .map_err(map_completion_error)?;
 
                     let mut mapper = OpenAiEventMapper::new();
                     Ok(map_cloud_completion_events(
-                        Box::pin(
-                            response_lines(response, includes_status_messages)
-                                .chain(usage_updated_event(usage))
-                                .chain(tool_use_limit_reached_event(tool_use_limit_reached)),
-                        ),
+                        retry_status_events(retry_status_rx)
+                            .chain(with_stall_deadline(deadline.0, response_lines(response, includes_status_messages)))
+                            .chain(usage_updated_event(usage))
+                            .chain(tool_use_limit_reached_event(tool_use_limit_reached))
+                            .boxed(),
                         move |event| mapper.map_event(event),
                     ))
                 });
                 async move { Ok(future.await?.boxed()) }.boxed()
             }
             cloud_llm_client::LanguageModelProvider::Google => {
-                let client = self.client.clone();
                 let request =
-                    into_google(request, self.model.id.to_string(), GoogleModelMode::Default);
-                let llm_api_token = self.llm_api_token.clone();
-                let future = self.request_limiter.stream(async move {
+                    into_google(request, model.id.to_string(), GoogleModelMode::Default);
+                let future = request_limiter.stream(async move {
+                    let (retry_status_tx, retry_status_rx) = mpsc::unbounded();
                     let PerformLlmCompletionResponse {
                         response,
                         usage,
@@ -1042,16 +2199,20 @@ impl LanguageModel for CloudLanguageModel {
                             provider_request: serde_json::to_value(&request)
                                 .map_err(|e| anyhow!(e))?,
                         },
+                        retry_config,
+                        deadline,
+                        retry_status_tx,
                     )
-                    .await?;
+                    .await
+                    .map_err(map_completion_error)?;
 
                     let mut mapper = GoogleEventMapper::new();
                     Ok(map_cloud_completion_events(
-                        Box::pin(
-                            response_lines(response, includes_status_messages)
-                                .chain(usage_updated_event(usage))
-                                .chain(tool_use_limit_reached_event(tool_use_limit_reached)),
-                        ),
+                        retry_status_events(retry_status_rx)
+                            .chain(with_stall_deadline(deadline.0, response_lines(response, includes_status_messages)))
+                            .chain(usage_updated_event(usage))
+                            .chain(tool_use_limit_reached_event(tool_use_limit_reached))
+                            .boxed(),
                         move |event| mapper.map_event(event),
                     ))
                 });
@@ -1062,7 +2223,7 @@ impl LanguageModel for CloudLanguageModel {
 }
 
 fn map_cloud_completion_events<T, F>(
-    stream: Pin<Box<dyn Stream<Item = Result<CompletionEvent<T>>> + Send>>,
+    stream: Pin<Box<dyn Stream<Item = Result<(Option<String>, CompletionEvent<T>)>> + Send>>,
     mut map_callback: F,
 ) -> BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>
 where
@@ -1075,12 +2236,27 @@ where
         .flat_map(move |event| {
             futures::stream::iter(match event {
                 Err(error) => {
-                    vec![Err(LanguageModelCompletionError::from(error))]
+                    vec![Err(match error.downcast::<CompletionDeadlineExceeded>() {
+                        Ok(error) => LanguageModelCompletionError::from_http_status(
+                            PROVIDER_NAME,
+                            StatusCode::REQUEST_TIMEOUT,
+                            error.to_string(),
+                            None,
+                        ),
+                        Err(error) => LanguageModelCompletionError::from(error),
+                    })]
                 }
-                Ok(CompletionEvent::Status(event)) => {
-                    vec![Ok(LanguageModelCompletionEvent::StatusUpdate(event))]
+                Ok((event_name, event)) => {
+                    if let Some(event_name) = &event_name {
+                        log::debug!("zed.dev completion SSE event: {event_name}");
+                    }
+                    match event {
+                        CompletionEvent::Status(event) => {
+                            vec![Ok(LanguageModelCompletionEvent::StatusUpdate(event))]
+                        }
+                        CompletionEvent::Event(event) => map_callback(event),
+                    }
                 }
-                Ok(CompletionEvent::Event(event)) => map_callback(event),
             })
         })
         .boxed()
@@ -1088,48 +2264,128 @@ where
 
 fn usage_updated_event<T>(
     usage: Option<ModelRequestUsage>,
-) -> impl Stream<Item = Result<CompletionEvent<T>>> {
+) -> impl Stream<Item = Result<(Option<String>, CompletionEvent<T>)>> {
     futures::stream::iter(usage.map(|usage| {
-        Ok(CompletionEvent::Status(
-            CompletionRequestStatus::UsageUpdated {
+        Ok((
+            None,
+            CompletionEvent::Status(CompletionRequestStatus::UsageUpdated {
                 amount: usage.amount as usize,
                 limit: usage.limit,
-            },
+            }),
         ))
     }))
 }
 
 fn tool_use_limit_reached_event<T>(
     tool_use_limit_reached: bool,
-) -> impl Stream<Item = Result<CompletionEvent<T>>> {
+) -> impl Stream<Item = Result<(Option<String>, CompletionEvent<T>)>> {
     futures::stream::iter(tool_use_limit_reached.then(|| {
-        Ok(CompletionEvent::Status(
-            CompletionRequestStatus::ToolUseLimitReached,
+        Ok((
+            None,
+            CompletionEvent::Status(CompletionRequestStatus::ToolUseLimitReached),
         ))
     }))
 }
 
+/// Turns the retry statuses `perform_llm_completion` sent while retrying
+/// the request into completion events, so the UI can show a "retrying…"
+/// indicator instead of just sitting idle during the delay. Chained in
+/// ahead of the response stream proper, since every retry happens before
+/// that stream exists.
+fn retry_status_events<T>(
+    retry_status_rx: mpsc::UnboundedReceiver<CompletionRequestStatus>,
+) -> impl Stream<Item = Result<(Option<String>, CompletionEvent<T>)>> {
+    retry_status_rx.map(|status| Ok((None, CompletionEvent::Status(status))))
+}
+
+/// Whether a completion response is framed as newline-delimited JSON (one
+/// JSON value per line) or as a proper Server-Sent Events stream (`data:`
+/// lines accumulated until a blank-line boundary).
+#[derive(Clone, Copy)]
+enum ResponseFraming {
+    NdJson,
+    Sse,
+}
+
+fn response_framing(response: &Response<AsyncBody>) -> ResponseFraming {
+    let is_sse = response
+        .headers()
+        .get(http_client::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+    if is_sse {
+        ResponseFraming::Sse
+    } else {
+        ResponseFraming::NdJson
+    }
+}
+
 fn response_lines<T: DeserializeOwned>(
     response: Response<AsyncBody>,
     includes_status_messages: bool,
-) -> impl Stream<Item = Result<CompletionEvent<T>>> {
+) -> impl Stream<Item = Result<(Option<String>, CompletionEvent<T>)>> {
+    let framing = response_framing(&response);
     futures::stream::try_unfold(
         (String::new(), BufReader::new(response.into_body())),
         move |(mut line, mut body)| async move {
-            match body.read_line(&mut line).await {
-                Ok(0) => Ok(None),
-                Ok(_) => {
-                    let event = if includes_status_messages {
-                        serde_json::from_str::<CompletionEvent<T>>(&line)?
-                    } else {
-                        CompletionEvent::Event(serde_json::from_str::<T>(&line)?)
-                    };
+            let (data, event_name) = match framing {
+                ResponseFraming::NdJson => match body.read_line(&mut line).await {
+                    Ok(0) => return Ok(None),
+                    Ok(_) => (std::mem::take(&mut line), None),
+                    Err(e) => return Err(e.into()),
+                },
+                ResponseFraming::Sse => {
+                    let mut data = String::new();
+                    let mut event_name = None;
+                    loop {
+                        line.clear();
+                        match body.read_line(&mut line).await {
+                            Ok(0) => {
+                                if data.is_empty() {
+                                    return Ok(None);
+                                }
+                                break;
+                            }
+                            Err(e) => return Err(e.into()),
+                            Ok(_) => {}
+                        }
 
-                    line.clear();
-                    Ok(Some((event, (line, body))))
+                        let trimmed = line.trim_end_matches(['\r', '\n']);
+                        if trimmed.is_empty() {
+                            // Blank line: end of this event. Keep reading if we
+                            // haven't accumulated any `data:` yet (e.g. the
+                            // stream opened with a comment or retry directive).
+                            if data.is_empty() {
+                                continue;
+                            }
+                            break;
+                        }
+                        if trimmed.starts_with(':') {
+                            continue; // comment line, per the SSE spec
+                        }
+                        if let Some(value) = trimmed.strip_prefix("event:") {
+                            event_name = Some(value.strip_prefix(' ').unwrap_or(value).to_string());
+                            continue;
+                        }
+                        if let Some(value) = trimmed.strip_prefix("data:") {
+                            if !data.is_empty() {
+                                data.push('\n');
+                            }
+                            data.push_str(value.strip_prefix(' ').unwrap_or(value));
+                        }
+                        // `id:`/`retry:` lines are accepted but this client
+                        // doesn't act on them.
+                    }
+                    (data, event_name)
                 }
-                Err(e) => Err(e.into()),
-            }
+            };
+
+            let event = if includes_status_messages {
+                serde_json::from_str::<CompletionEvent<T>>(&data)?
+            } else {
+                CompletionEvent::Event(serde_json::from_str::<T>(&data)?)
+            };
+            Ok(Some(((event_name, event), (line, body))))
         },
     )
 }
@@ -1368,7 +2624,7 @@ impl Component for ZedAiConfiguration {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use http_client::http::{HeaderMap, StatusCode};
+    use http_client::http::{HeaderMap, HeaderValue, StatusCode, header::RETRY_AFTER};
     use language_model::LanguageModelCompletionError;
 
     #[test]
@@ -1515,4 +2771,283 @@ mod tests {
             ),
         }
     }
+
+    #[test]
+    fn test_parse_control_plane_error() {
+        // Nested control-plane envelope with a recognized reason and a
+        // user-facing message should surface that message verbatim.
+        let error_body = r#"{
+            "status": {
+                "details": {
+                    "error_info": { "reason": "QUOTA_EXCEEDED", "metadata": {} },
+                    "user_facing_message": { "message": "You've used all your included requests." }
+                }
+            }
+        }"#;
+
+        let (reason, message) = parse_control_plane_error(error_body)
+            .expect("expected the nested control-plane envelope to parse");
+        assert_eq!(reason, ControlPlaneErrorReason::QuotaExceeded);
+        assert_eq!(message, "You've used all your included requests.");
+
+        let api_error = ApiError {
+            status: StatusCode::FORBIDDEN,
+            body: error_body.to_string(),
+            headers: HeaderMap::new(),
+        };
+        let completion_error: LanguageModelCompletionError = api_error.into();
+        match completion_error {
+            LanguageModelCompletionError::UpstreamProviderError { message, status, .. } => {
+                assert_eq!(message, "You've used all your included requests.");
+                assert_eq!(status, StatusCode::FORBIDDEN);
+            }
+            _ => panic!(
+                "Expected UpstreamProviderError for control-plane quota error, got: {:?}",
+                completion_error
+            ),
+        }
+
+        // An unrecognized reason should map to `Unknown` rather than failing
+        // to parse, and still propagate the user-facing message.
+        let error_body = r#"{
+            "status": {
+                "details": {
+                    "error_info": { "reason": "SOMETHING_NEW", "metadata": {} },
+                    "user_facing_message": { "message": "Something went wrong." }
+                }
+            }
+        }"#;
+
+        let (reason, message) = parse_control_plane_error(error_body)
+            .expect("expected the nested control-plane envelope to parse");
+        assert_eq!(reason, ControlPlaneErrorReason::Unknown);
+        assert_eq!(message, "Something went wrong.");
+
+        // A missing `details` object doesn't match the envelope shape at
+        // all, so callers should fall back to the simpler error handling
+        // rather than erroring out.
+        let error_body = r#"{"status": {}}"#;
+        assert!(parse_control_plane_error(error_body).is_none());
+
+        let api_error = ApiError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            body: error_body.to_string(),
+            headers: HeaderMap::new(),
+        };
+        let completion_error: LanguageModelCompletionError = api_error.into();
+        match completion_error {
+            LanguageModelCompletionError::ApiInternalServerError { provider, .. } => {
+                assert_eq!(provider, PROVIDER_NAME);
+            }
+            _ => panic!(
+                "Expected ApiInternalServerError fallback for malformed control-plane envelope, got: {:?}",
+                completion_error
+            ),
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_headers_fallback() {
+        // A plain `Retry-After: 42` header should be used when the body
+        // carries no `retry_after` of its own.
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("42"));
+
+        let api_error = ApiError {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            body: "Rate limited".to_string(),
+            headers,
+        };
+        let completion_error: LanguageModelCompletionError = api_error.into();
+        match completion_error {
+            LanguageModelCompletionError::RateLimitExceeded { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_secs(42)));
+            }
+            _ => panic!(
+                "Expected a retry_after recovered from headers, got: {:?}",
+                completion_error
+            ),
+        }
+
+        // An HTTP-date form should also be accepted.
+        let mut headers = HeaderMap::new();
+        let retry_at = Utc::now() + chrono::Duration::seconds(120);
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_str(&retry_at.to_rfc2822()).unwrap(),
+        );
+        let retry_after = parse_retry_after_header(&headers).expect("expected an HTTP-date to parse");
+        assert!(retry_after.as_secs() > 100 && retry_after.as_secs() <= 120);
+
+        // When both the body and a header carry a `retry_after`, the body
+        // should win, but remaining-quota headers should still be recovered
+        // for telemetry purposes.
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("5"));
+        headers.insert(
+            "x-ratelimit-remaining-requests",
+            HeaderValue::from_static("3"),
+        );
+        headers.insert("x-ratelimit-remaining-tokens", HeaderValue::from_static("100"));
+        headers.insert("x-ratelimit-reset-requests", HeaderValue::from_static("1m0s"));
+
+        let rate_limit_headers = parse_rate_limit_headers(&headers);
+        assert_eq!(rate_limit_headers.retry_after, Some(Duration::from_secs(5)));
+        assert_eq!(rate_limit_headers.remaining_requests, Some(3));
+        assert_eq!(rate_limit_headers.remaining_tokens, Some(100));
+        assert_eq!(rate_limit_headers.reset_requests.as_deref(), Some("1m0s"));
+
+        let error_body = r#"{"code":"upstream_http_429","message":"Upstream Anthropic rate limit exceeded.","retry_after":30.5}"#;
+        let api_error = ApiError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            body: error_body.to_string(),
+            headers,
+        };
+        let completion_error: LanguageModelCompletionError = api_error.into();
+        match completion_error {
+            LanguageModelCompletionError::UpstreamProviderError { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_secs_f64(30.5)));
+            }
+            _ => panic!(
+                "Expected the body's retry_after to win over the header, got: {:?}",
+                completion_error
+            ),
+        }
+    }
+
+    #[test]
+    fn test_retry_policy_classification() {
+        // upstream_http_429 with a retry_after should retry after that delay.
+        let error_body = r#"{"code":"upstream_http_429","message":"Upstream Anthropic rate limit exceeded.","retry_after":30.5}"#;
+        let api_error = ApiError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            body: error_body.to_string(),
+            headers: HeaderMap::new(),
+        };
+        let completion_error: LanguageModelCompletionError = api_error.into();
+        assert_eq!(
+            completion_error.retry_policy(),
+            RetryPolicy::RetryAfter(Duration::from_secs_f64(30.5))
+        );
+
+        // A 429 with no retry_after anywhere should fall back to our own
+        // exponential backoff.
+        let error_body = r#"{"code":"upstream_http_429","message":"Rate limited."}"#;
+        let api_error = ApiError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            body: error_body.to_string(),
+            headers: HeaderMap::new(),
+        };
+        let completion_error: LanguageModelCompletionError = api_error.into();
+        assert_eq!(completion_error.retry_policy(), RetryPolicy::RetryWithBackoff);
+
+        // A 503 (overloaded upstream) should also retry with backoff absent
+        // a provider-supplied delay.
+        let error_body = r#"{"code":"upstream_http_error","message":"Received an error from the Anthropic API: overloaded","upstream_status":503}"#;
+        let api_error = ApiError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            body: error_body.to_string(),
+            headers: HeaderMap::new(),
+        };
+        let completion_error: LanguageModelCompletionError = api_error.into();
+        assert_eq!(completion_error.retry_policy(), RetryPolicy::RetryWithBackoff);
+
+        // A plain internal server error (no upstream envelope) is not
+        // retryable: it won't resolve itself.
+        let api_error = ApiError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            body: "Regular internal server error".to_string(),
+            headers: HeaderMap::new(),
+        };
+        let completion_error: LanguageModelCompletionError = api_error.into();
+        assert_eq!(completion_error.retry_policy(), RetryPolicy::NonRetryable);
+
+        // A missed deadline should be retried with backoff, same as any
+        // other transient upstream failure, not silently dropped into the
+        // non-retryable default.
+        let err = completion_deadline_exceeded_as_completion_error(anyhow!(
+            CompletionDeadlineExceeded(Duration::from_secs(120))
+        ));
+        let completion_error = err.downcast::<LanguageModelCompletionError>().unwrap();
+        assert_eq!(completion_error.retry_policy(), RetryPolicy::RetryWithBackoff);
+    }
+
+    #[test]
+    fn test_api_error_debug_masks_sensitive_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "Authorization",
+            HeaderValue::from_static("Bearer super-secret-token"),
+        );
+        headers.insert("x-api-key", HeaderValue::from_static("also-secret"));
+        headers.insert("Cookie", HeaderValue::from_static("session=secret"));
+        headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+
+        let api_error = ApiError {
+            status: StatusCode::UNAUTHORIZED,
+            body: "unauthorized".to_string(),
+            headers,
+        };
+
+        let formatted = format!("{:?}", api_error);
+        assert!(!formatted.contains("super-secret-token"));
+        assert!(!formatted.contains("also-secret"));
+        assert!(!formatted.contains("session=secret"));
+        assert!(formatted.contains("<masked>"));
+        assert!(formatted.contains("application/json"));
+    }
+
+    #[test]
+    fn test_with_deadline() {
+        smol::block_on(async {
+            // A future that never resolves should yield the timeout error
+            // once the deadline elapses, rather than hanging forever.
+            let result: Result<()> =
+                with_deadline(Some(Duration::from_millis(10)), async {
+                    std::future::pending::<()>().await;
+                    Ok(())
+                })
+                .await;
+            assert!(
+                result
+                    .unwrap_err()
+                    .downcast_ref::<CompletionDeadlineExceeded>()
+                    .is_some()
+            );
+
+            // A future that finishes comfortably within the deadline should
+            // be unaffected.
+            let result = with_deadline(Some(Duration::from_secs(5)), async { Ok(42) }).await;
+            assert_eq!(result.unwrap(), 42);
+
+            // Disabling the deadline (`None`) should just await the future.
+            let result = with_deadline(None, async { Ok(7) }).await;
+            assert_eq!(result.unwrap(), 7);
+        });
+    }
+
+    #[test]
+    fn test_with_stall_deadline() {
+        smol::block_on(async {
+            // A stream that never yields another item should surface the
+            // timeout error rather than stalling forever.
+            let stream = futures::stream::pending::<Result<u32>>();
+            let mut stream = with_stall_deadline(Some(Duration::from_millis(10)), stream);
+            let first = stream.next().await.expect("stream should yield an item");
+            assert!(
+                first
+                    .unwrap_err()
+                    .downcast_ref::<CompletionDeadlineExceeded>()
+                    .is_some()
+            );
+
+            // A stream that finishes well within the deadline is unaffected.
+            let stream = futures::stream::iter(vec![Ok::<_, anyhow::Error>(1), Ok(2), Ok(3)]);
+            let items: Vec<u32> = with_stall_deadline(Some(Duration::from_secs(5)), stream)
+                .map(|item| item.unwrap())
+                .collect()
+                .await;
+            assert_eq!(items, vec![1, 2, 3]);
+        });
+    }
 }
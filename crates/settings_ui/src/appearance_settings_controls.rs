@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use fs::Fs;
+use gpui::{App, IntoElement, RenderOnce, Window};
+use settings::{Settings, update_settings_file};
+use theme::{ThemeSettings, ThemeSettingsContent};
+use ui::prelude::*;
+
+use crate::SettingsSearchEntry;
+
+#[derive(IntoElement)]
+pub struct AppearanceSettingsControls {}
+
+impl AppearanceSettingsControls {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn search_entries() -> Vec<SettingsSearchEntry> {
+        vec![
+            SettingsSearchEntry {
+                section: "Appearance".into(),
+                title: "Theme".into(),
+                keywords: &["color", "dark", "light", "scheme"],
+            },
+            SettingsSearchEntry {
+                section: "Appearance".into(),
+                title: "Icon Theme".into(),
+                keywords: &["icons", "file icons"],
+            },
+            SettingsSearchEntry {
+                section: "Appearance".into(),
+                title: "UI Font Size".into(),
+                keywords: &["font", "size", "zoom", "text size"],
+            },
+        ]
+    }
+
+    /// Number of appearance settings whose value has been overridden away
+    /// from the default in the user's settings file.
+    pub fn modified_count(cx: &App) -> usize {
+        let raw = ThemeSettings::get_global(cx).raw_user_settings();
+        [
+            raw.theme.is_some(),
+            raw.icon_theme.is_some(),
+            raw.ui_font_size.is_some(),
+        ]
+        .into_iter()
+        .filter(|modified| *modified)
+        .count()
+    }
+
+    /// Clears every appearance setting in `visible_titles` that the user has
+    /// overridden, reverting just the currently-visible settings back to
+    /// defaults rather than the whole section (so this is safe to call
+    /// while a search query has filtered the section down to a subset).
+    pub fn reset_all(fs: Arc<dyn Fs>, cx: &mut App, visible_titles: &HashSet<SharedString>) {
+        update_settings_file::<ThemeSettings>(fs, cx, |settings, _cx| {
+            if visible_titles.contains("Theme") {
+                settings.theme = None;
+            }
+            if visible_titles.contains("Icon Theme") {
+                settings.icon_theme = None;
+            }
+            if visible_titles.contains("UI Font Size") {
+                settings.ui_font_size = None;
+            }
+        });
+    }
+}
+
+impl RenderOnce for AppearanceSettingsControls {
+    fn render(self, _window: &mut Window, cx: &mut App) -> impl IntoElement {
+        let theme_settings = ThemeSettings::get_global(cx);
+        let raw = theme_settings.raw_user_settings();
+        let ui_font_size = theme_settings.ui_font_size(cx);
+        let fs = <dyn Fs>::global(cx);
+
+        v_flex()
+            .gap_2()
+            .child(setting_row(
+                "Theme",
+                theme_settings.active_theme.name.clone(),
+                raw.theme.is_some(),
+                {
+                    let fs = fs.clone();
+                    move |_window, cx| {
+                        update_settings_file::<ThemeSettings>(fs.clone(), cx, |settings, _cx| {
+                            settings.theme = None;
+                        });
+                    }
+                },
+            ))
+            .child(setting_row(
+                "Icon Theme",
+                theme_settings.active_icon_theme.name.clone(),
+                raw.icon_theme.is_some(),
+                {
+                    let fs = fs.clone();
+                    move |_window, cx| {
+                        update_settings_file::<ThemeSettings>(fs.clone(), cx, |settings, _cx| {
+                            settings.icon_theme = None;
+                        });
+                    }
+                },
+            ))
+            .child(setting_row(
+                "UI Font Size",
+                ui_font_size.to_string(),
+                raw.ui_font_size.is_some(),
+                {
+                    let fs = fs.clone();
+                    move |_window, cx| {
+                        update_settings_file::<ThemeSettings>(fs.clone(), cx, |settings, _cx| {
+                            settings.ui_font_size = None;
+                        });
+                    }
+                },
+            ))
+    }
+}
+
+fn setting_row(
+    label: impl Into<SharedString>,
+    value: impl Into<SharedString>,
+    is_modified: bool,
+    on_reset: impl Fn(&mut Window, &mut App) + 'static,
+) -> impl IntoElement {
+    h_flex()
+        .justify_between()
+        .child(
+            h_flex()
+                .gap_1()
+                .child(Label::new(label.into()))
+                .when(is_modified, |this| {
+                    this.child(Indicator::dot().color(Color::Accent))
+                }),
+        )
+        .child(
+            h_flex()
+                .gap_2()
+                .child(Label::new(value.into()).color(Color::Muted))
+                .when(is_modified, |this| {
+                    this.child(
+                        IconButton::new("reset", IconName::RotateCcw)
+                            .icon_size(IconSize::XSmall)
+                            .icon_color(Color::Muted)
+                            .tooltip(Tooltip::text("Reset to default"))
+                            .on_click(move |_, window, cx| on_reset(window, cx)),
+                    )
+                }),
+        )
+}
@@ -1,12 +1,18 @@
 mod appearance_settings_controls;
 
 use std::any::TypeId;
+use std::collections::HashSet;
+use std::sync::Arc;
 
 use command_palette_hooks::CommandPaletteFilter;
-use editor::EditorSettingsControls;
+use editor::{Editor, EditorEvent, EditorSettingsControls};
 use feature_flags::{FeatureFlag, FeatureFlagViewExt};
-use gpui::{App, Entity, EventEmitter, FocusHandle, Focusable, actions};
-use ui::prelude::*;
+use fs::Fs;
+use gpui::{
+    App, Entity, EventEmitter, FocusHandle, Focusable, FontWeight, Subscription,
+    UniformListScrollHandle, actions, uniform_list,
+};
+use ui::{TintColor, prelude::*};
 use workspace::item::{Item, ItemEvent};
 use workspace::{Workspace, with_active_or_new_workspace};
 
@@ -15,6 +21,90 @@ use crate::appearance_settings_controls::AppearanceSettingsControls;
 pub mod keybindings;
 pub mod ui_components;
 
+/// A single searchable entry contributed by a settings section, used to
+/// drive the settings page's fuzzy search bar.
+pub struct SettingsSearchEntry {
+    pub section: SharedString,
+    pub title: SharedString,
+    pub keywords: &'static [&'static str],
+}
+
+impl SettingsSearchEntry {
+    /// Returns the fuzzy match score for `query` against this entry's title,
+    /// or, when the title doesn't match, against the first keyword that
+    /// does, along with the byte offsets of the matched characters within
+    /// the title (empty when the match came from a keyword alone).
+    fn fuzzy_match(&self, query: &str) -> Option<(usize, Vec<usize>)> {
+        if query.is_empty() {
+            return Some((0, Vec::new()));
+        }
+
+        let title_match = fuzzy_match_positions(&self.title, query);
+        if let Some((score, positions)) = title_match {
+            return Some((score, positions));
+        }
+
+        self.keywords
+            .iter()
+            .find_map(|keyword| fuzzy_match_positions(keyword, query))
+            .map(|(score, _)| (score, Vec::new()))
+    }
+}
+
+/// A minimal subsequence-based fuzzy matcher: every character of `query`
+/// must appear in `haystack`, in order, case-insensitively. Consecutive
+/// matches score higher so that e.g. "them" ranks "Theme" above "The Machine".
+///
+/// Matching is done case-insensitively via `char::to_lowercase` on each
+/// `haystack` character in place, rather than by lowercasing the whole
+/// haystack up front: lowercasing can change a string's byte length (e.g.
+/// `İ` U+0130 lowercases to two code points), so positions taken from a
+/// separately-lengthed lowercased copy aren't valid byte offsets into the
+/// original `haystack` and can panic when later used to slice it.
+fn fuzzy_match_positions(haystack: &str, query: &str) -> Option<(usize, Vec<usize>)> {
+    let query_lower = query.to_lowercase();
+
+    let mut positions = Vec::with_capacity(query_lower.len());
+    let mut score = 0;
+    let mut last_match: Option<usize> = None;
+    let mut search_start = 0;
+
+    for query_char in query_lower.chars() {
+        let (absolute_offset, matched_char) = haystack[search_start..]
+            .char_indices()
+            .find_map(|(offset, c)| {
+                c.to_lowercase()
+                    .eq(std::iter::once(query_char))
+                    .then_some((search_start + offset, c))
+            })?;
+
+        score += if last_match == Some(absolute_offset.wrapping_sub(1)) {
+            3
+        } else {
+            1
+        };
+        positions.push(absolute_offset);
+        last_match = Some(absolute_offset);
+        search_start = absolute_offset + matched_char.len_utf8();
+    }
+
+    Some((score, positions))
+}
+
+/// A settings page section: its entries for the fuzzy search bar, and how
+/// many of its settings have been overridden from default along with how to
+/// reset a given subset of them back to default. Keeping these behind
+/// function pointers (rather than hardcoding `AppearanceSettingsControls`
+/// everywhere) means the "modified count" and "Reset All Visible" features
+/// automatically pick up any section added here, instead of silently
+/// ignoring it.
+struct SettingsSection {
+    name: SharedString,
+    search_entries: Vec<SettingsSearchEntry>,
+    modified_count: fn(&App) -> usize,
+    reset_all: fn(Arc<dyn Fs>, &mut App, &HashSet<SharedString>),
+}
+
 pub struct SettingsUiFeatureFlag;
 
 impl FeatureFlag for SettingsUiFeatureFlag {
@@ -81,14 +171,103 @@ pub fn init(cx: &mut App) {
 
 pub struct SettingsPage {
     focus_handle: FocusHandle,
+    search_editor: Entity<Editor>,
+    search_focus_handle: FocusHandle,
+    search_query: SharedString,
+    _search_editor_subscription: Subscription,
+    section_scroll_handle: UniformListScrollHandle,
 }
 
 impl SettingsPage {
     pub fn new(_workspace: &Workspace, cx: &mut Context<Workspace>) -> Entity<Self> {
-        cx.new(|cx| Self {
-            focus_handle: cx.focus_handle(),
+        cx.new(|cx| {
+            let search_editor = cx.new(|cx| {
+                let mut editor = Editor::single_line(cx);
+                editor.set_placeholder_text("Search settings…", cx);
+                editor
+            });
+
+            let search_editor_subscription =
+                cx.subscribe(&search_editor, |this: &mut Self, editor, event, cx| {
+                    if matches!(event, EditorEvent::BufferEdited) {
+                        this.search_query = editor.read(cx).text(cx).into();
+                        cx.notify();
+                    }
+                });
+
+            Self {
+                focus_handle: cx.focus_handle(),
+                search_focus_handle: search_editor.focus_handle(cx),
+                search_editor,
+                search_query: SharedString::default(),
+                _search_editor_subscription: search_editor_subscription,
+                section_scroll_handle: UniformListScrollHandle::new(),
+            }
         })
     }
+
+    /// Sections contributing entries to the search bar, modified count, and
+    /// "Reset All Visible". `Editor` is deliberately absent:
+    /// `EditorSettingsControls` lives in the `editor` crate and doesn't
+    /// expose `search_entries`/`modified_count`/`reset_all` yet, so it can't
+    /// participate until that crate grows them. It's still reachable (and
+    /// still rendered) via the section sidebar below.
+    fn sections(&self) -> [SettingsSection; 1] {
+        [SettingsSection {
+            name: "Appearance".into(),
+            search_entries: AppearanceSettingsControls::search_entries(),
+            modified_count: AppearanceSettingsControls::modified_count,
+            reset_all: AppearanceSettingsControls::reset_all,
+        }]
+    }
+
+    /// Total number of settings, across all sections, overridden away from
+    /// their default value.
+    fn modified_count(&self, cx: &App) -> usize {
+        self.sections()
+            .into_iter()
+            .map(|section| (section.modified_count)(cx))
+            .sum()
+    }
+
+    /// Index of the section currently scrolled to the top of the content
+    /// pane, used to highlight the matching sidebar entry.
+    fn active_section_index(&self) -> usize {
+        self.section_scroll_handle.logical_scroll_top().item_ix
+    }
+
+    /// Returns, for each section, the entries matching the current search
+    /// query ordered by match quality, along with the matched highlight
+    /// positions for each entry's title. A section with no matches is omitted
+    /// entirely so empty headings don't clutter the page.
+    fn matching_sections(&self) -> Vec<(SharedString, Vec<(SettingsSearchEntry, Vec<usize>)>)> {
+        self.sections()
+            .into_iter()
+            .filter_map(|section| {
+                let mut matches: Vec<(SettingsSearchEntry, usize, Vec<usize>)> = section
+                    .search_entries
+                    .into_iter()
+                    .filter_map(|entry| {
+                        let (score, positions) = entry.fuzzy_match(&self.search_query)?;
+                        Some((entry, score, positions))
+                    })
+                    .collect();
+
+                if matches.is_empty() {
+                    return None;
+                }
+
+                matches.sort_by(|a, b| b.1.cmp(&a.1));
+                Some((
+                    section.name,
+                    matches
+                        .into_iter()
+                        .map(|(entry, _, positions)| (entry, positions))
+                        .collect(),
+                ))
+            })
+            .collect()
+    }
 }
 
 impl EventEmitter<ItemEvent> for SettingsPage {}
@@ -106,8 +285,13 @@ impl Item for SettingsPage {
         Some(Icon::new(IconName::Settings))
     }
 
-    fn tab_content_text(&self, _detail: usize, _cx: &App) -> SharedString {
-        "Settings".into()
+    fn tab_content_text(&self, _detail: usize, cx: &App) -> SharedString {
+        let modified_count = self.modified_count(cx);
+        if modified_count > 0 {
+            format!("Settings ({modified_count})").into()
+        } else {
+            "Settings".into()
+        }
     }
 
     fn show_toolbar(&self) -> bool {
@@ -121,24 +305,184 @@ impl Item for SettingsPage {
 
 impl Render for SettingsPage {
     fn render(&mut self, _: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let matching_sections = self.matching_sections();
+        let query = self.search_query.clone();
+
+        let modified_count = self.modified_count(cx);
+        // Restrict "Reset All Visible" to whatever the current search query
+        // (if any) actually leaves on screen: `matching_sections` already
+        // matches every entry when the query is empty, so this also covers
+        // the no-search case.
+        let reset_targets: Vec<(fn(Arc<dyn Fs>, &mut App, &HashSet<SharedString>), HashSet<SharedString>)> =
+            self.sections()
+                .into_iter()
+                .map(|section| {
+                    let visible_titles = matching_sections
+                        .iter()
+                        .find(|(name, _)| *name == section.name)
+                        .map(|(_, entries)| {
+                            entries.iter().map(|(entry, _)| entry.title.clone()).collect()
+                        })
+                        .unwrap_or_default();
+                    (section.reset_all, visible_titles)
+                })
+                .collect();
+        // The button itself must only show up when there's something in
+        // `reset_targets` for its click handler to actually act on, not
+        // merely when *some* setting somewhere is modified.
+        let has_visible_reset_targets = reset_targets
+            .iter()
+            .any(|(_, visible_titles)| !visible_titles.is_empty());
+
         v_flex()
             .p_4()
             .size_full()
             .gap_4()
-            .child(Label::new("Settings").size(LabelSize::Large))
             .child(
-                v_flex().gap_1().child(Label::new("Appearance")).child(
-                    v_flex()
-                        .elevation_2(cx)
-                        .child(AppearanceSettingsControls::new()),
-                ),
+                h_flex()
+                    .justify_between()
+                    .child(Label::new("Settings").size(LabelSize::Large))
+                    .when(modified_count > 0 && has_visible_reset_targets, |this| {
+                        this.child(
+                            Button::new("reset_all_visible", "Reset All Visible")
+                                .icon(IconName::RotateCcw)
+                                .icon_position(IconPosition::Start)
+                                .icon_size(IconSize::XSmall)
+                                .label_size(LabelSize::Small)
+                                .on_click(move |_, _window, cx| {
+                                    let fs = <dyn Fs>::global(cx);
+                                    for (reset_all, visible_titles) in &reset_targets {
+                                        reset_all(fs.clone(), cx, visible_titles);
+                                    }
+                                }),
+                        )
+                    }),
             )
             .child(
-                v_flex().gap_1().child(Label::new("Editor")).child(
-                    v_flex()
-                        .elevation_2(cx)
-                        .child(EditorSettingsControls::new()),
-                ),
+                h_flex()
+                    .elevation_2(cx)
+                    .px_2()
+                    .gap_2()
+                    .track_focus(&self.search_focus_handle)
+                    .child(Icon::new(IconName::MagnifyingGlass).color(Color::Muted))
+                    .child(div().flex_1().child(self.search_editor.clone())),
             )
+            .when(!query.is_empty(), |this| {
+                this.children(matching_sections.into_iter().map(|(section, entries)| {
+                    v_flex().gap_1().child(Label::new(section)).child(
+                        v_flex().elevation_2(cx).children(entries.into_iter().map(
+                            |(entry, highlight_positions)| {
+                                render_highlighted_entry_title(&entry.title, &highlight_positions)
+                            },
+                        )),
+                    )
+                }))
+            })
+            .when(query.is_empty(), |this| {
+                let section_names = ["Appearance", "Editor"];
+                let active_index = self.active_section_index();
+                let scroll_handle = self.section_scroll_handle.clone();
+
+                this.child(
+                    h_flex()
+                        .flex_1()
+                        .items_start()
+                        .gap_4()
+                        .child(
+                            v_flex()
+                                .w(rems(10.))
+                                .flex_shrink_0()
+                                .gap_1()
+                                .children(section_names.iter().enumerate().map(
+                                    |(ix, name)| {
+                                        let scroll_handle = scroll_handle.clone();
+                                        Button::new(("settings-section-nav", ix), *name)
+                                            .full_width()
+                                            .style(if ix == active_index {
+                                                ButtonStyle::Tinted(TintColor::Accent)
+                                            } else {
+                                                ButtonStyle::Subtle
+                                            })
+                                            .on_click(cx.listener(move |_this, _, _window, cx| {
+                                                scroll_handle.scroll_to_item(ix);
+                                                // `scroll_to_item` doesn't itself cause the
+                                                // page to re-render, so without this the
+                                                // sidebar highlight wouldn't follow a click
+                                                // until some unrelated event (e.g. typing in
+                                                // the search box) happened to redraw it.
+                                                cx.notify();
+                                            }))
+                                    },
+                                )),
+                        )
+                        .child(
+                            uniform_list(
+                                "settings-sections",
+                                section_names.len(),
+                                move |range, _window, cx| {
+                                    range
+                                        .map(|ix| match ix {
+                                            0 => v_flex()
+                                                .gap_1()
+                                                .pb_4()
+                                                .child(Label::new("Appearance"))
+                                                .child(
+                                                    v_flex()
+                                                        .elevation_2(cx)
+                                                        .child(AppearanceSettingsControls::new()),
+                                                )
+                                                .into_any_element(),
+                                            1 => v_flex()
+                                                .gap_1()
+                                                .pb_4()
+                                                .child(Label::new("Editor"))
+                                                .child(
+                                                    v_flex()
+                                                        .elevation_2(cx)
+                                                        .child(EditorSettingsControls::new()),
+                                                )
+                                                .into_any_element(),
+                                            _ => unreachable!("only two settings sections exist"),
+                                        })
+                                        .collect()
+                                },
+                            )
+                            .track_scroll(self.section_scroll_handle.clone())
+                            .flex_1(),
+                        ),
+                )
+            })
+    }
+}
+
+/// Renders a setting's title with the fuzzy-matched characters emphasized,
+/// falling back to a plain label when there's nothing to highlight (e.g. the
+/// match came from a keyword alias rather than the title itself).
+fn render_highlighted_entry_title(title: &SharedString, positions: &[usize]) -> impl IntoElement {
+    if positions.is_empty() {
+        return Label::new(title.clone()).into_any_element();
+    }
+
+    let mut highlighted = h_flex();
+    let mut last_end = 0;
+    for &position in positions {
+        if position > last_end {
+            highlighted = highlighted.child(Label::new(title[last_end..position].to_string()));
+        }
+        let char_end = title[position..]
+            .chars()
+            .next()
+            .map_or(position, |c| position + c.len_utf8());
+        highlighted = highlighted.child(
+            Label::new(title[position..char_end].to_string())
+                .color(Color::Accent)
+                .weight(FontWeight::BOLD),
+        );
+        last_end = char_end;
     }
+    if last_end < title.len() {
+        highlighted = highlighted.child(Label::new(title[last_end..].to_string()));
+    }
+
+    highlighted.into_any_element()
 }